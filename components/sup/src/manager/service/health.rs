@@ -0,0 +1,31 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// The result of a `health_check` hook or native probe, ordered from
+/// healthiest to least healthy by `health_check::severity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthCheck {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl Default for HealthCheck {
+    /// Used before a hook has run for the first time, or when its exit
+    /// status couldn't be classified.
+    fn default() -> HealthCheck {
+        HealthCheck::Unknown
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Built-in HTTP/TCP health probes. These let a service declare health
+//! reporting through its config instead of shipping a `health_check` hook
+//! script, at the cost of only being able to express "can I connect" /
+//! "did this return 2xx" checks rather than arbitrary logic.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::result;
+use std::time::{Duration, Instant};
+
+use serde_json::Value as Json;
+use url::Url;
+
+use super::health::HealthCheck;
+
+const DEFAULT_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_WARNING_THRESHOLD_MS: u64 = 1_000;
+
+/// A service's native probe configuration, parsed from the `health_check`
+/// section of its rendered config. Either or both of `http`/`tcp` may be
+/// present; when both are, the worst-case result across them wins.
+#[derive(Debug, Clone)]
+pub struct ProbeConfig {
+    pub http: Option<HttpProbe>,
+    pub tcp: Option<TcpProbe>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpProbe {
+    url: String,
+    min_status: u16,
+    max_status: u16,
+    warning_threshold_ms: u64,
+    timeout_ms: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TcpProbe {
+    addr: SocketAddr,
+    timeout_ms: u64,
+}
+
+impl ProbeConfig {
+    /// Parses a `[health_check]` section such as:
+    ///
+    /// ```toml
+    /// [health_check.http]
+    /// url = "http://localhost:8080/status"
+    /// min_status = 200
+    /// max_status = 299
+    ///
+    /// [health_check.tcp]
+    /// port = 5432
+    /// ```
+    ///
+    /// Returns `None` when the section is absent or declares neither probe,
+    /// so the caller can fall back to its compiled `health_check` hook.
+    pub fn from_json(data: &Json) -> Option<Self> {
+        let section = data.get("health_check")?;
+        let http = section.get("http").and_then(HttpProbe::from_json);
+        let tcp = section.get("tcp").and_then(TcpProbe::from_json);
+        if http.is_none() && tcp.is_none() {
+            return None;
+        }
+        Some(ProbeConfig {
+            http: http,
+            tcp: tcp,
+        })
+    }
+}
+
+impl HttpProbe {
+    fn from_json(data: &Json) -> Option<Self> {
+        let url = data.get("url").and_then(|v| v.as_str())?.to_string();
+        Some(HttpProbe {
+            url: url,
+            min_status: data.get("min_status").and_then(|v| v.as_u64()).unwrap_or(200) as u16,
+            max_status: data.get("max_status").and_then(|v| v.as_u64()).unwrap_or(299) as u16,
+            warning_threshold_ms: data.get("warning_threshold_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(DEFAULT_WARNING_THRESHOLD_MS),
+            timeout_ms: data.get("timeout_ms").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_TIMEOUT_MS),
+        })
+    }
+}
+
+impl TcpProbe {
+    fn from_json(data: &Json) -> Option<Self> {
+        let port = data.get("port").and_then(|v| v.as_u64())? as u16;
+        let addr = ("localhost", port).to_socket_addrs().ok()?.next()?;
+        Some(TcpProbe {
+            addr: addr,
+            timeout_ms: data.get("timeout_ms").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_TIMEOUT_MS),
+        })
+    }
+}
+
+/// Runs every probe declared in `probe` and reduces the results to a
+/// single worst-case `HealthCheck`.
+pub fn run(probe: &ProbeConfig) -> HealthCheck {
+    let mut worst = HealthCheck::Ok;
+    if let Some(ref http) = probe.http {
+        worst = worse(worst, run_http(http));
+    }
+    if let Some(ref tcp) = probe.tcp {
+        worst = worse(worst, run_tcp(tcp));
+    }
+    worst
+}
+
+fn worse(a: HealthCheck, b: HealthCheck) -> HealthCheck {
+    if severity(&b) > severity(&a) { b } else { a }
+}
+
+fn severity(check: &HealthCheck) -> u8 {
+    match *check {
+        HealthCheck::Ok => 0,
+        HealthCheck::Warning => 1,
+        HealthCheck::Unknown => 2,
+        HealthCheck::Critical => 3,
+    }
+}
+
+fn run_tcp(probe: &TcpProbe) -> HealthCheck {
+    let timeout = Duration::from_millis(probe.timeout_ms);
+    match TcpStream::connect_timeout(&probe.addr, timeout) {
+        Ok(_) => HealthCheck::Ok,
+        Err(err) => {
+            match classify_io_err(err) {
+                ProbeError::Refused => HealthCheck::Critical,
+                ProbeError::Timeout | ProbeError::Other(_) => HealthCheck::Unknown,
+            }
+        }
+    }
+}
+
+fn run_http(probe: &HttpProbe) -> HealthCheck {
+    let timeout = Duration::from_millis(probe.timeout_ms);
+    let started = Instant::now();
+    match perform_get(probe, timeout) {
+        Ok(status) => {
+            let elapsed_ms = duration_as_millis(started.elapsed());
+            if status < probe.min_status || status > probe.max_status {
+                HealthCheck::Critical
+            } else if elapsed_ms >= probe.warning_threshold_ms {
+                HealthCheck::Warning
+            } else {
+                HealthCheck::Ok
+            }
+        }
+        Err(ProbeError::Refused) => HealthCheck::Critical,
+        Err(ProbeError::Timeout) | Err(ProbeError::Other(_)) => HealthCheck::Unknown,
+    }
+}
+
+fn duration_as_millis(d: Duration) -> u64 {
+    d.as_secs() * 1_000 + (d.subsec_nanos() / 1_000_000) as u64
+}
+
+/// Why `perform_get` failed, distinguished only as far as `run_http` needs
+/// to classify the result: a refused/reset connection is a live, unhealthy
+/// service (`Critical`), while a timed-out connect/read tells us nothing
+/// about the service's state (`Unknown`), same as any other plumbing error.
+enum ProbeError {
+    Refused,
+    Timeout,
+    Other(String),
+}
+
+fn classify_io_err(err: io::Error) -> ProbeError {
+    match err.kind() {
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => ProbeError::Refused,
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ProbeError::Timeout,
+        _ => ProbeError::Other(err.to_string()),
+    }
+}
+
+/// A deliberately minimal HTTP/1.0 GET, just enough to read a status line.
+fn perform_get(probe: &HttpProbe, timeout: Duration) -> result::Result<u16, ProbeError> {
+    let url = Url::parse(&probe.url).map_err(|e| ProbeError::Other(e.to_string()))?;
+    let host = url.host_str()
+        .ok_or_else(|| ProbeError::Other("probe URL has no host".to_string()))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(classify_io_err)?
+        .next()
+        .ok_or_else(|| ProbeError::Other("probe URL did not resolve to an address".to_string()))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(classify_io_err)?;
+    stream.set_read_timeout(Some(timeout)).ok();
+    stream.set_write_timeout(Some(timeout)).ok();
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                           url.path(),
+                           host);
+    stream.write_all(request.as_bytes()).map_err(classify_io_err)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(classify_io_err)?;
+    let status_line = response.lines()
+        .next()
+        .ok_or_else(|| ProbeError::Other("empty response".to_string()))?;
+    status_line.split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| ProbeError::Other(format!("malformed status line, {}", status_line)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worse_picks_the_higher_severity_result() {
+        assert_eq!(worse(HealthCheck::Ok, HealthCheck::Warning), HealthCheck::Warning);
+        assert_eq!(worse(HealthCheck::Critical, HealthCheck::Unknown), HealthCheck::Critical);
+        assert_eq!(worse(HealthCheck::Warning, HealthCheck::Warning), HealthCheck::Warning);
+        assert_eq!(worse(HealthCheck::Unknown, HealthCheck::Ok), HealthCheck::Unknown);
+    }
+
+    #[test]
+    fn severity_orders_ok_warning_unknown_critical() {
+        assert!(severity(&HealthCheck::Ok) < severity(&HealthCheck::Warning));
+        assert!(severity(&HealthCheck::Warning) < severity(&HealthCheck::Unknown));
+        assert!(severity(&HealthCheck::Unknown) < severity(&HealthCheck::Critical));
+    }
+
+    #[test]
+    fn classify_io_err_maps_refused_and_reset_to_refused() {
+        assert!(match classify_io_err(io::Error::new(io::ErrorKind::ConnectionRefused, "x")) {
+            ProbeError::Refused => true,
+            _ => false,
+        });
+        assert!(match classify_io_err(io::Error::new(io::ErrorKind::ConnectionReset, "x")) {
+            ProbeError::Refused => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn classify_io_err_maps_timeouts_to_timeout() {
+        assert!(match classify_io_err(io::Error::new(io::ErrorKind::TimedOut, "x")) {
+            ProbeError::Timeout => true,
+            _ => false,
+        });
+        assert!(match classify_io_err(io::Error::new(io::ErrorKind::WouldBlock, "x")) {
+            ProbeError::Timeout => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn classify_io_err_maps_other_errors_to_other() {
+        assert!(match classify_io_err(io::Error::new(io::ErrorKind::PermissionDenied, "x")) {
+            ProbeError::Other(_) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn duration_as_millis_combines_whole_and_subsecond_parts() {
+        assert_eq!(duration_as_millis(Duration::from_millis(1_500)), 1_500);
+        assert_eq!(duration_as_millis(Duration::new(2, 999_999)), 2_000);
+    }
+}
@@ -19,12 +19,17 @@ use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::process::{Child, ExitStatus};
 use std::result;
+use std::sync::RwLock;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use hcore;
 use hcore::service::ServiceGroup;
+use libc;
 use serde::{Serialize, Serializer};
 
 use super::health;
+use super::health_check;
 use error::Result;
 use manager::service::ServiceConfig;
 use supervisor::RuntimeConfig;
@@ -34,6 +39,12 @@ use util;
 pub const HOOK_PERMISSIONS: u32 = 0o755;
 static LOGKEY: &'static str = "HK";
 
+/// How often `run_hook_script` polls `try_wait` on a running hook.
+const HOOK_POLL_INTERVAL_MS: u64 = 500;
+/// How long a hook is given to exit on its own after SIGTERM before we
+/// escalate to SIGKILL.
+const HOOK_KILL_GRACE_PERIOD_MS: u64 = 5_000;
+
 #[derive(Debug, Copy, Clone)]
 pub struct ExitCode(i32);
 
@@ -43,12 +54,25 @@ impl Default for ExitCode {
     }
 }
 
-pub trait Hook: fmt::Debug + Sized {
+/// Lets the `Hook` trait's default methods build a concrete hook type from
+/// a bare `RenderPair`, without needing to know about any extra fields a
+/// particular hook keeps alongside it (e.g. `HealthCheckHook`'s cached
+/// probe config).
+pub trait FromRenderPair {
+    fn from_render_pair(pair: RenderPair) -> Self;
+}
+
+pub trait Hook: fmt::Debug + Sized + FromRenderPair {
     type ExitValue: Default;
 
     fn file_name() -> &'static str;
 
-    fn load<C, T>(service_group: &ServiceGroup, concrete_path: C, template_path: T) -> Option<Self>
+    fn load<C, T>(service_group: &ServiceGroup,
+                   concrete_path: C,
+                   template_path: T,
+                   partials: &[(String, PathBuf)],
+                   defaults_enabled: bool)
+                   -> Option<Self>
         where C: AsRef<Path>,
               T: AsRef<Path>
     {
@@ -56,7 +80,7 @@ pub trait Hook: fmt::Debug + Sized {
         let template = template_path.as_ref().join(Self::file_name());
         match std::fs::metadata(&template) {
             Ok(_) => {
-                match Self::new(concrete, template) {
+                match Self::new(service_group, concrete, template, partials) {
                     Ok(hook) => Some(hook),
                     Err(err) => {
                         outputln!(preamble service_group, "Failed to load hook: {}", err);
@@ -65,6 +89,18 @@ pub trait Hook: fmt::Debug + Sized {
                 }
             }
             Err(_) => {
+                if defaults_enabled {
+                    if let Some(result) = Self::new_default(concrete) {
+                        return match result {
+                            Ok(hook) => Some(hook),
+                            Err(err) => {
+                                outputln!(preamble service_group,
+                                    "Failed to load default hook: {}", err);
+                                None
+                            }
+                        };
+                    }
+                }
                 debug!("{} not found at {}, not loading",
                        Self::file_name(),
                        template.display());
@@ -73,10 +109,33 @@ pub trait Hook: fmt::Debug + Sized {
         }
     }
 
-    fn new<C, T>(concrete_path: C, template_path: T) -> Result<Self>
+    fn new<C, T>(service_group: &ServiceGroup,
+                 concrete_path: C,
+                 template_path: T,
+                 partials: &[(String, PathBuf)])
+                 -> Result<Self>
         where C: Into<PathBuf>,
               T: AsRef<Path>;
 
+    /// The built-in template compiled into the binary for this hook type,
+    /// used in place of a package-supplied template when one isn't present
+    /// and defaults haven't been disabled. Hook types with no reasonable
+    /// default (e.g. `run`) simply keep the `None` here.
+    fn default_template() -> Option<&'static str> {
+        None
+    }
+
+    /// Build this hook from its built-in default template, if it has one.
+    /// `None` means this hook type has no default; `Some(Err(_))` means it
+    /// does, but compiling it failed.
+    fn new_default<C>(concrete_path: C) -> Option<Result<Self>>
+        where C: Into<PathBuf>
+    {
+        Self::default_template().map(|content| {
+            RenderPair::from_str(concrete_path, content).map(Self::from_render_pair)
+        })
+    }
+
     /// Compile a hook into it's destination service directory.
     fn compile(&self, cfg: &ServiceConfig) -> Result<()> {
         let toml = try!(cfg.to_toml());
@@ -94,24 +153,16 @@ pub trait Hook: fmt::Debug + Sized {
 
     /// Run a compiled hook.
     fn run(&self, service_group: &ServiceGroup, cfg: &RuntimeConfig) -> Self::ExitValue {
-        let mut child = match util::create_command(self.path(), &cfg.svc_user, &cfg.svc_group)
-            .spawn() {
-            Ok(child) => child,
-            Err(err) => {
-                outputln!(preamble service_group,
-                    "Hook failed to run, {}, {}", Self::file_name(), err);
-                return Self::ExitValue::default();
-            }
-        };
-        stream_output::<Self>(service_group, &mut child);
-        match child.wait() {
-            Ok(status) => self.handle_exit(service_group, &status),
-            Err(err) => {
-                outputln!(preamble service_group,
-                    "Hook failed to run, {}, {}", Self::file_name(), err);
-                Self::ExitValue::default()
-            }
-        }
+        run_hook_script(self, service_group, cfg)
+    }
+
+    /// How long this hook type is allowed to run before `run_hook_script`
+    /// terminates it, when the service config doesn't override it via
+    /// `RuntimeConfig::hook_timeout`. `None` means no timeout is enforced;
+    /// that's the right default for a hook like `run`, which is expected to
+    /// stay alive for as long as the service does.
+    fn default_timeout() -> Option<Duration> {
+        None
     }
 
     fn handle_exit(&self, group: &ServiceGroup, status: &ExitStatus) -> Self::ExitValue;
@@ -124,6 +175,12 @@ pub trait Hook: fmt::Debug + Sized {
 #[derive(Debug, Serialize)]
 pub struct FileUpdatedHook(RenderPair);
 
+impl FromRenderPair for FileUpdatedHook {
+    fn from_render_pair(pair: RenderPair) -> Self {
+        FileUpdatedHook(pair)
+    }
+}
+
 impl Hook for FileUpdatedHook {
     type ExitValue = bool;
 
@@ -131,14 +188,22 @@ impl Hook for FileUpdatedHook {
         "file_updated"
     }
 
-    fn new<C, T>(concrete_path: C, template_path: T) -> Result<Self>
+    fn new<C, T>(service_group: &ServiceGroup,
+                 concrete_path: C,
+                 template_path: T,
+                 partials: &[(String, PathBuf)])
+                 -> Result<Self>
         where C: Into<PathBuf>,
               T: AsRef<Path>
     {
-        let pair = RenderPair::new(concrete_path, template_path)?;
+        let pair = RenderPair::new(service_group, concrete_path, template_path, partials)?;
         Ok(FileUpdatedHook(pair))
     }
 
+    fn default_timeout() -> Option<Duration> {
+        Some(Duration::from_secs(60))
+    }
+
     fn handle_exit(&self, _: &ServiceGroup, status: &ExitStatus) -> Self::ExitValue {
         status.success()
     }
@@ -152,8 +217,18 @@ impl Hook for FileUpdatedHook {
     }
 }
 
-#[derive(Debug, Serialize)]
-pub struct HealthCheckHook(RenderPair);
+/// `health_check` has a second, script-free way to report health: if the
+/// service's config declares a `[health_check]` probe, `run` performs that
+/// probe directly instead of spawning the compiled hook. The probe config
+/// is cached at `compile` time, since `run` only gets a `RuntimeConfig`.
+#[derive(Debug)]
+pub struct HealthCheckHook(RenderPair, RwLock<Option<health_check::ProbeConfig>>);
+
+impl FromRenderPair for HealthCheckHook {
+    fn from_render_pair(pair: RenderPair) -> Self {
+        HealthCheckHook(pair, RwLock::new(None))
+    }
+}
 
 impl Hook for HealthCheckHook {
     type ExitValue = health::HealthCheck;
@@ -162,28 +237,63 @@ impl Hook for HealthCheckHook {
         "health_check"
     }
 
-    fn new<C, T>(concrete_path: C, template_path: T) -> Result<Self>
+    fn new<C, T>(service_group: &ServiceGroup,
+                 concrete_path: C,
+                 template_path: T,
+                 partials: &[(String, PathBuf)])
+                 -> Result<Self>
         where C: Into<PathBuf>,
               T: AsRef<Path>
     {
-        let pair = RenderPair::new(concrete_path, template_path)?;
-        Ok(HealthCheckHook(pair))
+        let pair = RenderPair::new(service_group, concrete_path, template_path, partials)?;
+        Ok(HealthCheckHook(pair, RwLock::new(None)))
+    }
+
+    fn default_template() -> Option<&'static str> {
+        Some(include_str!("default_hooks/health_check.hbs"))
+    }
+
+    fn compile(&self, cfg: &ServiceConfig) -> Result<()> {
+        let toml = try!(cfg.to_toml());
+        let svc_data = util::convert::toml_to_json(toml);
+        *self.1.write().expect("health check probe config lock poisoned") =
+            health_check::ProbeConfig::from_json(&svc_data);
+        let data = try!(self.template().render("hook", &svc_data));
+        let mut file = try!(std::fs::File::create(self.path()));
+        try!(file.write_all(data.as_bytes()));
+        try!(hcore::util::perm::set_owner(self.path(), &cfg.pkg.svc_user, &cfg.pkg.svc_group));
+        try!(hcore::util::perm::set_permissions(self.path(), HOOK_PERMISSIONS));
+        debug!("{} compiled to {}",
+               Self::file_name(),
+               self.path().display());
+        Ok(())
+    }
+
+    fn default_timeout() -> Option<Duration> {
+        Some(Duration::from_secs(30))
+    }
+
+    fn run(&self, service_group: &ServiceGroup, cfg: &RuntimeConfig) -> Self::ExitValue {
+        if let Some(ref probe) = *self.1.read().expect("health check probe config lock poisoned") {
+            return health_check::run(probe);
+        }
+        run_hook_script(self, service_group, cfg)
     }
 
     fn handle_exit(&self, service_group: &ServiceGroup, status: &ExitStatus) -> Self::ExitValue {
-        match status.code() {
-            Some(0) => health::HealthCheck::Ok,
-            Some(1) => health::HealthCheck::Warning,
-            Some(2) => health::HealthCheck::Critical,
-            Some(3) => health::HealthCheck::Unknown,
-            Some(code) => {
-                outputln!(preamble service_group,
-                    "Health check exited with an unknown status code, {}", code);
-                health::HealthCheck::default()
-            }
+        match classify_exit_code(status.code()) {
+            Some(check) => check,
             None => {
-                outputln!(preamble service_group,
-                    "{} exited without a status code", Self::file_name());
+                match status.code() {
+                    Some(code) => {
+                        outputln!(preamble service_group,
+                            "Health check exited with an unknown status code, {}", code);
+                    }
+                    None => {
+                        outputln!(preamble service_group,
+                            "{} exited without a status code", Self::file_name());
+                    }
+                }
                 health::HealthCheck::default()
             }
         }
@@ -198,9 +308,38 @@ impl Hook for HealthCheckHook {
     }
 }
 
+impl Serialize for HealthCheckHook {
+    fn serialize<S>(&self, serializer: &mut S) -> result::Result<(), S::Error>
+        where S: Serializer
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+/// Maps a `health_check` hook's exit code to the `HealthCheck` it reports,
+/// per the convention every `health_check` hook is expected to follow: `0`
+/// healthy, `1` warning, `2` critical, `3` unknown. `None` means the code
+/// (or its absence) isn't one of those, and the caller should log that fact
+/// itself before falling back to `HealthCheck::default()`.
+fn classify_exit_code(code: Option<i32>) -> Option<health::HealthCheck> {
+    match code {
+        Some(0) => Some(health::HealthCheck::Ok),
+        Some(1) => Some(health::HealthCheck::Warning),
+        Some(2) => Some(health::HealthCheck::Critical),
+        Some(3) => Some(health::HealthCheck::Unknown),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct InitHook(RenderPair);
 
+impl FromRenderPair for InitHook {
+    fn from_render_pair(pair: RenderPair) -> Self {
+        InitHook(pair)
+    }
+}
+
 impl Hook for InitHook {
     type ExitValue = ExitCode;
 
@@ -208,14 +347,26 @@ impl Hook for InitHook {
         "init"
     }
 
-    fn new<C, T>(concrete_path: C, template_path: T) -> Result<Self>
+    fn new<C, T>(service_group: &ServiceGroup,
+                 concrete_path: C,
+                 template_path: T,
+                 partials: &[(String, PathBuf)])
+                 -> Result<Self>
         where C: Into<PathBuf>,
               T: AsRef<Path>
     {
-        let pair = RenderPair::new(concrete_path, template_path)?;
+        let pair = RenderPair::new(service_group, concrete_path, template_path, partials)?;
         Ok(InitHook(pair))
     }
 
+    fn default_template() -> Option<&'static str> {
+        Some(include_str!("default_hooks/init.hbs"))
+    }
+
+    fn default_timeout() -> Option<Duration> {
+        Some(Duration::from_secs(60))
+    }
+
     fn handle_exit(&self, service_group: &ServiceGroup, status: &ExitStatus) -> Self::ExitValue {
         match status.code() {
             Some(code) => ExitCode(code),
@@ -239,6 +390,12 @@ impl Hook for InitHook {
 #[derive(Debug, Serialize)]
 pub struct ReconfigureHook(RenderPair);
 
+impl FromRenderPair for ReconfigureHook {
+    fn from_render_pair(pair: RenderPair) -> Self {
+        ReconfigureHook(pair)
+    }
+}
+
 impl Hook for ReconfigureHook {
     type ExitValue = ExitCode;
 
@@ -246,14 +403,22 @@ impl Hook for ReconfigureHook {
         "reconfigure"
     }
 
-    fn new<C, T>(concrete_path: C, template_path: T) -> Result<Self>
+    fn new<C, T>(service_group: &ServiceGroup,
+                 concrete_path: C,
+                 template_path: T,
+                 partials: &[(String, PathBuf)])
+                 -> Result<Self>
         where C: Into<PathBuf>,
               T: AsRef<Path>
     {
-        let pair = RenderPair::new(concrete_path, template_path)?;
+        let pair = RenderPair::new(service_group, concrete_path, template_path, partials)?;
         Ok(ReconfigureHook(pair))
     }
 
+    fn default_timeout() -> Option<Duration> {
+        Some(Duration::from_secs(60))
+    }
+
     fn handle_exit(&self, service_group: &ServiceGroup, status: &ExitStatus) -> Self::ExitValue {
         match status.code() {
             Some(code) => ExitCode(code),
@@ -277,6 +442,12 @@ impl Hook for ReconfigureHook {
 #[derive(Debug, Serialize)]
 pub struct RunHook(RenderPair);
 
+impl FromRenderPair for RunHook {
+    fn from_render_pair(pair: RenderPair) -> Self {
+        RunHook(pair)
+    }
+}
+
 impl Hook for RunHook {
     type ExitValue = ExitCode;
 
@@ -284,11 +455,15 @@ impl Hook for RunHook {
         "run"
     }
 
-    fn new<C, T>(concrete_path: C, template_path: T) -> Result<Self>
+    fn new<C, T>(service_group: &ServiceGroup,
+                 concrete_path: C,
+                 template_path: T,
+                 partials: &[(String, PathBuf)])
+                 -> Result<Self>
         where C: Into<PathBuf>,
               T: AsRef<Path>
     {
-        let pair = RenderPair::new(concrete_path, template_path)?;
+        let pair = RenderPair::new(service_group, concrete_path, template_path, partials)?;
         Ok(RunHook(pair))
     }
 
@@ -315,6 +490,12 @@ impl Hook for RunHook {
 #[derive(Debug, Serialize)]
 pub struct SmokeTestHook(RenderPair);
 
+impl FromRenderPair for SmokeTestHook {
+    fn from_render_pair(pair: RenderPair) -> Self {
+        SmokeTestHook(pair)
+    }
+}
+
 impl Hook for SmokeTestHook {
     type ExitValue = health::SmokeCheck;
 
@@ -322,14 +503,26 @@ impl Hook for SmokeTestHook {
         "smoke_test"
     }
 
-    fn new<C, T>(concrete_path: C, template_path: T) -> Result<Self>
+    fn new<C, T>(service_group: &ServiceGroup,
+                 concrete_path: C,
+                 template_path: T,
+                 partials: &[(String, PathBuf)])
+                 -> Result<Self>
         where C: Into<PathBuf>,
               T: AsRef<Path>
     {
-        let pair = RenderPair::new(concrete_path, template_path)?;
+        let pair = RenderPair::new(service_group, concrete_path, template_path, partials)?;
         Ok(SmokeTestHook(pair))
     }
 
+    fn default_template() -> Option<&'static str> {
+        Some(include_str!("default_hooks/smoke_test.hbs"))
+    }
+
+    fn default_timeout() -> Option<Duration> {
+        Some(Duration::from_secs(30))
+    }
+
     fn handle_exit(&self, service_group: &ServiceGroup, status: &ExitStatus) -> Self::ExitValue {
         match status.code() {
             Some(0) => health::SmokeCheck::Ok,
@@ -394,18 +587,35 @@ impl HookTable {
     }
 
     /// Read all available hook templates from the table's package directory into the table.
-    pub fn load_hooks<T, U>(mut self, service_group: &ServiceGroup, hooks: T, templates: U) -> Self
+    /// `disable_defaults` lists hook names (e.g. `"health_check"`) for
+    /// which the supervisor's built-in default template should *not* be
+    /// substituted when the package doesn't ship its own, so an operator
+    /// can still opt a service out of default behavior entirely.
+    pub fn load_hooks<T, U>(mut self,
+                             service_group: &ServiceGroup,
+                             hooks: T,
+                             templates: U,
+                             disable_defaults: &[String])
+                             -> Self
         where T: AsRef<Path>,
               U: AsRef<Path>
     {
         if let Some(meta) = std::fs::metadata(templates.as_ref()).ok() {
             if meta.is_dir() {
-                self.file_updated = FileUpdatedHook::load(service_group, &hooks, &templates);
-                self.health_check = HealthCheckHook::load(service_group, &hooks, &templates);
-                self.init = InitHook::load(service_group, &hooks, &templates);
-                self.reconfigure = ReconfigureHook::load(service_group, &hooks, &templates);
-                self.run = RunHook::load(service_group, &hooks, &templates);
-                self.smoke_test = SmokeTestHook::load(service_group, &hooks, &templates);
+                let partials = load_partials(service_group, templates.as_ref());
+                let defaults_enabled = |name: &str| !disable_defaults.iter().any(|n| n == name);
+                self.file_updated = FileUpdatedHook::load(service_group, &hooks, &templates, &partials,
+                    defaults_enabled(FileUpdatedHook::file_name()));
+                self.health_check = HealthCheckHook::load(service_group, &hooks, &templates, &partials,
+                    defaults_enabled(HealthCheckHook::file_name()));
+                self.init = InitHook::load(service_group, &hooks, &templates, &partials,
+                    defaults_enabled(InitHook::file_name()));
+                self.reconfigure = ReconfigureHook::load(service_group, &hooks, &templates, &partials,
+                    defaults_enabled(ReconfigureHook::file_name()));
+                self.run = RunHook::load(service_group, &hooks, &templates, &partials,
+                    defaults_enabled(RunHook::file_name()));
+                self.smoke_test = SmokeTestHook::load(service_group, &hooks, &templates, &partials,
+                    defaults_enabled(SmokeTestHook::file_name()));
             }
         }
         debug!("{}, Hooks loaded, destination={}, templates={}",
@@ -431,17 +641,102 @@ struct RenderPair {
 }
 
 impl RenderPair {
-    pub fn new<C, T>(concrete_path: C, template_path: T) -> Result<Self>
+    pub fn new<C, T>(service_group: &ServiceGroup,
+                     concrete_path: C,
+                     template_path: T,
+                     partials: &[(String, PathBuf)])
+                     -> Result<Self>
         where C: Into<PathBuf>,
               T: AsRef<Path>
     {
         let mut template = Template::new();
+        if let Some(dir) = template_path.as_ref().parent() {
+            template.register_script_helpers(dir)?;
+        }
+        for &(ref name, ref path) in partials {
+            if let Err(err) = template.register_partial(name, path) {
+                outputln!(preamble service_group,
+                    "Failed to register partial '{}', skipping it: {}", name, err);
+            }
+        }
         template.register_template_file("hook", template_path.as_ref())?;
         Ok(RenderPair {
             path: concrete_path.into(),
             template: template,
         })
     }
+
+    /// Build a `RenderPair` from an in-memory template string instead of a
+    /// file on disk, so a built-in default hook compiles through the same
+    /// `Template` pipeline (and ends up with the same permissions and
+    /// ownership) as a package-supplied one.
+    pub fn from_str<C>(concrete_path: C, content: &str) -> Result<Self>
+        where C: Into<PathBuf>
+    {
+        let mut template = Template::new();
+        template.register_template_string("hook", content)?;
+        Ok(RenderPair {
+            path: concrete_path.into(),
+            template: template,
+        })
+    }
+}
+
+/// Walk `templates/partials` under a package's template directory and
+/// return every partial found there as a `(name, path)` pair, where `name`
+/// is the partial's path relative to `partials/` with its extension
+/// stripped (e.g. `partials/common_env.hbs` registers as
+/// `partials/common_env`, usable from any hook as `{{> partials/common_env }}`).
+/// The same list is handed to every hook's `RenderPair`, so a single edit
+/// to a shared partial is picked up by all of them on the next compile.
+fn load_partials(service_group: &ServiceGroup, templates: &Path) -> Vec<(String, PathBuf)> {
+    let mut partials = Vec::new();
+    let root = templates.join("partials");
+    if let Ok(meta) = std::fs::metadata(&root) {
+        if meta.is_dir() {
+            walk_partials(service_group, &root, &root, &mut partials);
+        }
+    }
+    partials
+}
+
+fn walk_partials(service_group: &ServiceGroup,
+                  root: &Path,
+                  dir: &Path,
+                  partials: &mut Vec<(String, PathBuf)>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            outputln!(preamble service_group,
+                "Failed to read partials directory {}, {}", dir.display(), err);
+            return;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                outputln!(preamble service_group, "Failed to read partial entry, {}", err);
+                continue;
+            }
+        };
+        let path = entry.path();
+        if path.is_dir() {
+            walk_partials(service_group, root, &path, partials);
+            continue;
+        }
+        match path.strip_prefix(root).ok().and_then(|p| p.to_str()) {
+            Some(rel) => {
+                let stem = Path::new(rel).with_extension("");
+                let name = format!("partials/{}", stem.to_string_lossy());
+                partials.push((name, path.clone()));
+            }
+            None => {
+                outputln!(preamble service_group,
+                    "Failed to register partial at {}, skipping", path.display());
+            }
+        }
+    }
 }
 
 impl fmt::Debug for RenderPair {
@@ -458,24 +753,156 @@ impl Serialize for RenderPair {
     }
 }
 
-fn stream_output<H: Hook>(service_group: &ServiceGroup, process: &mut Child) {
-    let preamble_str = stream_preamble::<H>(service_group);
-    if let Some(ref mut stdout) = process.stdout {
-        for line in BufReader::new(stdout).lines() {
-            if let Some(ref l) = line.ok() {
-                outputln!(preamble preamble_str, l);
+/// The default `Hook::run` behavior: spawn the compiled script, stream its
+/// output concurrently with waiting on it, and classify its exit status via
+/// `handle_exit`. If the hook's timeout elapses first, it's killed and the
+/// type's default `ExitValue` is reported instead. Pulled out as a free
+/// function so hooks that override `run` (e.g. `HealthCheckHook`'s native
+/// probe mode) can still fall back to it.
+fn run_hook_script<H: Hook>(hook: &H, service_group: &ServiceGroup, cfg: &RuntimeConfig) -> H::ExitValue {
+    let mut child = match util::create_command(hook.path(), &cfg.svc_user, &cfg.svc_group).spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            outputln!(preamble service_group,
+                "Hook failed to run, {}, {}", H::file_name(), err);
+            return H::ExitValue::default();
+        }
+    };
+    let timeout = cfg.hook_timeout(H::file_name()).or_else(H::default_timeout);
+    let started = Instant::now();
+    let output = stream_output::<H>(service_group, &mut child);
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                join_output(output);
+                outputln!(preamble service_group,
+                    "{} hook completed in {}", H::file_name(), format_elapsed(started.elapsed()));
+                return hook.handle_exit(service_group, &status);
+            }
+            Ok(None) => {
+                if timeout.map(|t| started.elapsed() >= t).unwrap_or(false) {
+                    outputln!(preamble service_group,
+                        "{} hook exceeded its {} timeout, terminating",
+                        H::file_name(), format_elapsed(timeout.unwrap()));
+                    terminate_then_kill(&mut child);
+                    join_output(output);
+                    return H::ExitValue::default();
+                }
+                thread::sleep(Duration::from_millis(HOOK_POLL_INTERVAL_MS));
+            }
+            Err(err) => {
+                outputln!(preamble service_group,
+                    "Hook failed to run, {}, {}", H::file_name(), err);
+                join_output(output);
+                return H::ExitValue::default();
             }
         }
     }
-    if let Some(ref mut stderr) = process.stderr {
-        for line in BufReader::new(stderr).lines() {
-            if let Some(ref l) = line.ok() {
-                outputln!(preamble preamble_str, l);
+}
+
+/// Sends SIGTERM, gives the child `HOOK_KILL_GRACE_PERIOD_MS` to exit on its
+/// own, then SIGKILLs it.
+fn terminate_then_kill(child: &mut Child) {
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let deadline = Instant::now() + Duration::from_millis(HOOK_KILL_GRACE_PERIOD_MS);
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => return,
+            Ok(None) => {
+                if Instant::now() >= deadline {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return;
+                }
+                thread::sleep(Duration::from_millis(HOOK_POLL_INTERVAL_MS));
             }
+            Err(_) => return,
         }
     }
 }
 
+fn format_elapsed(d: Duration) -> String {
+    format!("{}.{:03}s", d.as_secs(), d.subsec_nanos() / 1_000_000)
+}
+
+/// Drains the hook's stdout and stderr on their own background threads, one
+/// per pipe, so it can run concurrently with `run_hook_script`'s wait/
+/// timeout loop. Draining both pipes on a single thread would deadlock: a
+/// hook that blocks writing to a full stderr pipe while a reader thread is
+/// still parked reading stdout to EOF would never get drained, and so would
+/// never exit, holding the hook open until its timeout fires.
+fn stream_output<H: Hook>(service_group: &ServiceGroup, process: &mut Child) -> Vec<thread::JoinHandle<()>> {
+    let preamble_str = stream_preamble::<H>(service_group);
+    let mut handles = Vec::new();
+    if let Some(stdout) = process.stdout.take() {
+        let preamble_str = preamble_str.clone();
+        handles.push(thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if let Some(ref l) = line.ok() {
+                    outputln!(preamble preamble_str, l);
+                }
+            }
+        }));
+    }
+    if let Some(stderr) = process.stderr.take() {
+        handles.push(thread::spawn(move || {
+            for line in BufReader::new(stderr).lines() {
+                if let Some(ref l) = line.ok() {
+                    outputln!(preamble preamble_str, l);
+                }
+            }
+        }));
+    }
+    handles
+}
+
+/// Waits for every stdout/stderr drain thread `stream_output` spawned.
+fn join_output(handles: Vec<thread::JoinHandle<()>>) {
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
 fn stream_preamble<H: Hook>(service_group: &ServiceGroup) -> String {
     format!("{} hook[{}]:", service_group, H::file_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn classify_exit_code_maps_the_four_documented_codes() {
+        assert_eq!(classify_exit_code(Some(0)), Some(health::HealthCheck::Ok));
+        assert_eq!(classify_exit_code(Some(1)), Some(health::HealthCheck::Warning));
+        assert_eq!(classify_exit_code(Some(2)), Some(health::HealthCheck::Critical));
+        assert_eq!(classify_exit_code(Some(3)), Some(health::HealthCheck::Unknown));
+    }
+
+    #[test]
+    fn classify_exit_code_rejects_undocumented_or_missing_codes() {
+        assert_eq!(classify_exit_code(Some(4)), None);
+        assert_eq!(classify_exit_code(Some(-1)), None);
+        assert_eq!(classify_exit_code(None), None);
+    }
+
+    #[test]
+    fn format_elapsed_pads_subsecond_millis() {
+        assert_eq!(format_elapsed(Duration::from_millis(1_005)), "1.005s");
+        assert_eq!(format_elapsed(Duration::from_millis(40)), "0.040s");
+    }
+
+    #[test]
+    fn terminate_then_kill_stops_a_child_that_ignores_sigterm() {
+        let mut child = Command::new("sh")
+            .args(&["-c", "trap '' TERM; sleep 30"])
+            .spawn()
+            .expect("failed to spawn test child");
+        terminate_then_kill(&mut child);
+        let status = child.try_wait().expect("failed to reap killed child");
+        assert!(status.is_some(), "child should have been terminated");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,45 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Everything `hooks::run_hook_script` needs to actually execute a compiled
+/// hook that isn't already baked into the rendered script itself: the
+/// user/group to run it as, and any per-hook timeout overrides the
+/// service's config declares.
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    pub svc_user: String,
+    pub svc_group: String,
+    hook_timeouts: HashMap<String, Duration>,
+}
+
+impl RuntimeConfig {
+    pub fn new(svc_user: String, svc_group: String, hook_timeouts: HashMap<String, Duration>) -> Self {
+        RuntimeConfig {
+            svc_user: svc_user,
+            svc_group: svc_group,
+            hook_timeouts: hook_timeouts,
+        }
+    }
+
+    /// The timeout override for a hook, by file name (e.g. `"run"`,
+    /// `"health_check"`), declared by the service's config under
+    /// `[hook_timeouts]`. `None` means the service didn't override this
+    /// hook, and `Hook::default_timeout` should be used instead.
+    pub fn hook_timeout(&self, hook_name: &str) -> Option<Duration> {
+        self.hook_timeouts.get(hook_name).cloned()
+    }
+}
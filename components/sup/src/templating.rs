@@ -0,0 +1,246 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A thin wrapper around `handlebars::Handlebars` used to compile hook and
+//! config templates. Beyond the bare rendering support handlebars gives us
+//! for free, this module registers a standard set of helpers that package
+//! authors can call from any hook template (`toJson`, `toToml`, ...), so
+//! they don't have to shell out to `jq`/`sed` in the compiled hook itself.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::result;
+
+use base64;
+use handlebars::{Handlebars, Helper, HelperDef, RenderContext, RenderError};
+use rhai::{Engine, Scope};
+use serde_json;
+use serde_json::Value as Json;
+use toml;
+
+use error::Result;
+
+/// A `Template` is a `Handlebars` registry with our standard helpers
+/// already wired in. Every `RenderPair` gets its own `Template`, so a
+/// helper registered here is available to every hook and config template
+/// the supervisor compiles.
+pub struct Template(Handlebars);
+
+impl Template {
+    pub fn new() -> Self {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("toToml", Box::new(to_toml_helper));
+        handlebars.register_helper("toJson", Box::new(to_json_helper));
+        handlebars.register_helper("toLowercase", Box::new(to_lowercase_helper));
+        handlebars.register_helper("toUppercase", Box::new(to_uppercase_helper));
+        handlebars.register_helper("strReplace", Box::new(str_replace_helper));
+        handlebars.register_helper("base64Encode", Box::new(base64_encode_helper));
+        handlebars.register_helper("pkgPathFor", Box::new(pkg_path_for_helper));
+        Template(handlebars)
+    }
+
+    pub fn register_template_file<P>(&mut self, name: &str, path: P) -> Result<()>
+        where P: AsRef<Path>
+    {
+        self.0.register_template_file(name, path.as_ref())?;
+        Ok(())
+    }
+
+    /// Register a template from an in-memory string rather than a file on
+    /// disk. Used to compile the supervisor's built-in default hooks
+    /// (`include_str!`-embedded in the binary) through the same pipeline as
+    /// package-supplied templates.
+    pub fn register_template_string(&mut self, name: &str, content: &str) -> Result<()> {
+        self.0.register_template_string(name, content)?;
+        Ok(())
+    }
+
+    /// Register a single partial under `name`, so a template can reference
+    /// it with `{{> name}}`. `name`/`path` pairs typically come from walking
+    /// a package's `templates/partials` directory; see `hooks::load_partials`.
+    /// Partials are registered one at a time (rather than as a batch) so a
+    /// caller can skip a single malformed partial without losing the rest.
+    pub fn register_partial<P>(&mut self, name: &str, path: P) -> Result<()>
+        where P: AsRef<Path>
+    {
+        self.0.register_template_file(name, path.as_ref())?;
+        Ok(())
+    }
+
+    pub fn render(&self, name: &str, ctx: &Json) -> Result<String> {
+        let rendered = self.0.render(name, ctx)?;
+        Ok(rendered)
+    }
+
+    /// Register a `ScriptHelper` for every sibling `*.rhai` file found in
+    /// `dir`, named after the script's file stem, e.g. `compute_threads.rhai`
+    /// becomes the `{{ compute_threads ... }}` helper. Missing directories
+    /// are not an error; a hook simply has no script helpers available.
+    pub fn register_script_helpers<P>(&mut self, dir: P) -> Result<()>
+        where P: AsRef<Path>
+    {
+        let entries = match fs::read_dir(dir.as_ref()) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("rhai") {
+                continue;
+            }
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let script = fs::read_to_string(&path)?;
+            self.0.register_helper(&name, Box::new(ScriptHelper::new(script, Engine::new())));
+        }
+        Ok(())
+    }
+}
+
+/// A Handlebars helper backed by a small, sandboxed Rhai script. The
+/// script receives each helper parameter positionally as `arg0`, `arg1`,
+/// ... and its return value is written to the template output. The engine
+/// is constructed once when the helper is registered (no filesystem or
+/// process access is ever registered on it) and reused across every
+/// render, so a hook template can do real conditional or arithmetic logic
+/// without shelling out from the compiled hook.
+struct ScriptHelper {
+    script: String,
+    engine: Engine,
+}
+
+impl ScriptHelper {
+    fn new(script: String, engine: Engine) -> Self {
+        ScriptHelper {
+            script: script,
+            engine: engine,
+        }
+    }
+}
+
+impl HelperDef for ScriptHelper {
+    fn call(&self, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> result::Result<(), RenderError> {
+        let mut scope = Scope::new();
+        for (i, param) in h.params().iter().enumerate() {
+            scope.push(format!("arg{}", i), param.value().to_string());
+        }
+        match self.engine.eval_with_scope::<String>(&mut scope, &self.script) {
+            Ok(value) => {
+                rc.writer.write(value.into_bytes().as_ref())?;
+                Ok(())
+            }
+            Err(err) => Err(RenderError::new(format!("script helper failed, {}", err))),
+        }
+    }
+}
+
+/// Helpers receive the already-rendered parameter subtree and return a
+/// `String`; `render_helper_value` centralizes the "pull the first
+/// parameter, convert it, write it to the output" plumbing shared by the
+/// single-parameter helpers below (`strReplace` and `pkgPathFor` read
+/// their parameters themselves).
+fn render_helper_value<F>(h: &Helper, rc: &mut RenderContext, convert: F) -> result::Result<(), RenderError>
+    where F: Fn(&Json) -> result::Result<String, String>
+{
+    let param = h.param(0)
+        .ok_or_else(|| RenderError::new("expected one parameter"))?
+        .value();
+    match convert(param) {
+        Ok(rendered) => {
+            rc.writer.write(rendered.into_bytes().as_ref())?;
+            Ok(())
+        }
+        Err(err) => Err(RenderError::new(err)),
+    }
+}
+
+fn to_toml_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> result::Result<(), RenderError> {
+    render_helper_value(h, rc, |value| {
+        toml::to_string_pretty(value).map_err(|e| format!("unable to convert to TOML, {}", e))
+    })
+}
+
+fn to_json_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> result::Result<(), RenderError> {
+    render_helper_value(h, rc, |value| {
+        serde_json::to_string_pretty(value).map_err(|e| format!("unable to convert to JSON, {}", e))
+    })
+}
+
+fn to_lowercase_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> result::Result<(), RenderError> {
+    render_helper_value(h, rc, |value| {
+        value.as_str()
+            .map(|s| s.to_lowercase())
+            .ok_or_else(|| "toLowercase expects a string parameter".to_string())
+    })
+}
+
+fn to_uppercase_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> result::Result<(), RenderError> {
+    render_helper_value(h, rc, |value| {
+        value.as_str()
+            .map(|s| s.to_uppercase())
+            .ok_or_else(|| "toUppercase expects a string parameter".to_string())
+    })
+}
+
+fn str_replace_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> result::Result<(), RenderError> {
+    let subject = h.param(0)
+        .and_then(|v| v.value().as_str())
+        .ok_or_else(|| RenderError::new("strReplace expects (subject, from, to)"))?;
+    let from = h.param(1)
+        .and_then(|v| v.value().as_str())
+        .ok_or_else(|| RenderError::new("strReplace expects (subject, from, to)"))?;
+    let to = h.param(2)
+        .and_then(|v| v.value().as_str())
+        .ok_or_else(|| RenderError::new("strReplace expects (subject, from, to)"))?;
+    rc.writer.write(subject.replace(from, to).into_bytes().as_ref())?;
+    Ok(())
+}
+
+fn base64_encode_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> result::Result<(), RenderError> {
+    render_helper_value(h, rc, |value| {
+        value.as_str()
+            .map(|s| base64::encode(s.as_bytes()))
+            .ok_or_else(|| "base64Encode expects a string parameter".to_string())
+    })
+}
+
+/// Resolves the install path of one of the service's runtime dependencies
+/// by package name, e.g. `{{pkgPathFor "core/jre8"}}`. Dependency paths are
+/// looked up from the `deps` array the supervisor includes in every
+/// rendered context.
+fn pkg_path_for_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> result::Result<(), RenderError> {
+    let ident = h.param(0)
+        .and_then(|v| v.value().as_str())
+        .ok_or_else(|| RenderError::new("pkgPathFor expects a package identifier"))?;
+    let deps = rc.context()
+        .data()
+        .get("deps")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| RenderError::new("no dependency data available to pkgPathFor"))?;
+    let path = deps.iter()
+        .find(|dep| {
+            dep.get("ident")
+                .and_then(|v| v.as_str())
+                .map(|s| s == ident || s.ends_with(&format!("/{}", ident)))
+                .unwrap_or(false)
+        })
+        .and_then(|dep| dep.get("path"))
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| RenderError::new(format!("no dependency matching '{}'", ident)))?;
+    rc.writer.write(path.as_bytes())?;
+    Ok(())
+}